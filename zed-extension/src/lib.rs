@@ -1,65 +1,308 @@
+use std::fs;
+use std::path::PathBuf;
+
 use zed_extension_api as zed;
 
-struct TriggerSystemExtension;
+/// Name of the npm package that bundles the compiled `server.bundle.js`.
+const SERVER_NPM_PACKAGE: &str = "trigger-system-language-server";
 
-impl zed::Extension for TriggerSystemExtension {
-    fn new() -> Self {
-        Self
+/// Path to the bundle inside the installed npm package, relative to the
+/// extension's working directory.
+const SERVER_NPM_PATH: &str = "node_modules/trigger-system-language-server/dist/server.bundle.js";
+
+/// Repository whose GitHub releases publish prebuilt, platform-specific
+/// `trigger-system-lsp` binaries.
+const SERVER_GITHUB_REPO: &str = "nglmercer/trigger_system";
+
+struct TriggerSystemExtension {
+    cached_server_path: Option<String>,
+    cached_binary_path: Option<String>,
+}
+
+/// Default trigger file globs, evaluation mode, and diagnostics severity
+/// sent to the server when the user hasn't configured their own, shared by
+/// `language_server_initialization_options` and
+/// `language_server_workspace_configuration` so the two can't drift apart.
+fn default_trigger_system_config() -> zed::serde_json::Value {
+    zed::serde_json::json!({
+        "triggerFileGlobs": ["**/*.trigger", "**/*.triggers.json"],
+        "evaluationMode": "strict",
+        "diagnostics": {
+            "severity": "warning"
+        }
+    })
+}
+
+/// Builds the command-line args for launching the LSP: `leading` (the
+/// server path and/or `--stdio`), followed by `binary.arguments` from the
+/// user's `lsp` settings, if any.
+fn build_args(lsp_settings: Option<&zed::settings::Binary>, leading: Vec<String>) -> Vec<String> {
+    let mut args = leading;
+    if let Some(extra_args) = lsp_settings.and_then(|binary| binary.arguments.clone()) {
+        args.extend(extra_args);
     }
+    args
+}
 
-    fn language_server_command(
+impl TriggerSystemExtension {
+    /// Resolves the path to the LSP bundle, installing it via npm if
+    /// necessary.
+    ///
+    /// Resolution order:
+    /// 1. The cached path from a previous resolution, if it still exists.
+    /// 2. One of the hardcoded development/production bundle locations.
+    /// 3. An npm install of [`SERVER_NPM_PACKAGE`], re-installing only when
+    ///    the installed version differs from the latest.
+    fn server_script_path(
         &mut self,
-        _language_server_id: &zed::LanguageServerId,
+        language_server_id: &zed::LanguageServerId,
         worktree: &zed::Worktree,
-    ) -> Result<zed::Command, String> {
-        // Find node in the PATH
-        let node_path = worktree
-            .which("node")
-            .ok_or_else(|| "node executable not found in PATH. Please install Node.js.".to_string())?;
+    ) -> Result<String, String> {
+        if let Some(path) = &self.cached_server_path {
+            if fs::metadata(path).is_ok() {
+                return Ok(path.clone());
+            }
+        }
 
         // 1. Get the worktree root path (for development mode)
         let worktree_root = worktree.root_path();
         let worktree_dir = std::path::Path::new(&worktree_root);
-        
+
         // 2. Identify potential LSP bundle locations
         // - Development: When running from the repo, it's in vscode-extension/dist/lsp/
         // - Production: When installed as an extension, it's in the extension root
         let lsp_paths = [
             worktree_dir.join("vscode-extension/dist/lsp/server.bundle.js"),
-            std::path::PathBuf::from("server.bundle.js"),
+            PathBuf::from("server.bundle.js"),
             // In some environments, it might be at /server.bundle.js
-            std::path::PathBuf::from("/server.bundle.js"),
+            PathBuf::from("/server.bundle.js"),
         ];
-        
-        let mut lsp_path = None;
+
         for path in &lsp_paths {
             if path.exists() {
-                lsp_path = Some(path.clone());
-                break;
+                let path = path.to_string_lossy().to_string();
+                self.cached_server_path = Some(path.clone());
+                return Ok(path);
             }
         }
-        
-        // Final fallback: try to find it in the current directory if path.exists() is unreliable
-        let lsp_path = match lsp_path {
-            Some(path) => path,
-            None => {
-                // If we couldn't find it via exists(), check if we can at least return a likely path
-                // or provide a very detailed error message.
-                return Err(format!(
-                    "Trigger System LSP not found.\nSearched in:\n1. {:?}\n2. server.bundle.js\n3. /server.bundle.js\n\nEnsure 'bun run build:lsp' was run and the bundle is in the extension folder.",
-                    lsp_paths[0]
-                ));
+
+        // 3. Fall back to installing the bundle from npm.
+        zed::set_language_server_installation_status(
+            language_server_id,
+            &zed::LanguageServerInstallationStatus::CheckingForUpdate,
+        );
+
+        let latest_version = zed::npm_package_latest_version(SERVER_NPM_PACKAGE)?;
+        let installed_version = zed::npm_package_installed_version(SERVER_NPM_PACKAGE)?;
+
+        if installed_version.as_deref() != Some(latest_version.as_str()) {
+            zed::set_language_server_installation_status(
+                language_server_id,
+                &zed::LanguageServerInstallationStatus::Downloading,
+            );
+            zed::npm_install_package(SERVER_NPM_PACKAGE, &latest_version)?;
+        }
+
+        zed::set_language_server_installation_status(
+            language_server_id,
+            &zed::LanguageServerInstallationStatus::None,
+        );
+
+        self.cached_server_path = Some(SERVER_NPM_PATH.to_string());
+        Ok(SERVER_NPM_PATH.to_string())
+    }
+
+    /// Downloads the platform-specific `trigger-system-lsp` binary from the
+    /// latest GitHub release, for environments where npm isn't available.
+    /// Returns the path to the extracted binary.
+    fn download_server_from_github(
+        &mut self,
+        language_server_id: &zed::LanguageServerId,
+    ) -> Result<String, String> {
+        if let Some(path) = &self.cached_binary_path {
+            if fs::metadata(path).is_ok() {
+                return Ok(path.clone());
             }
+        }
+
+        zed::set_language_server_installation_status(
+            language_server_id,
+            &zed::LanguageServerInstallationStatus::CheckingForUpdate,
+        );
+
+        let release = zed::latest_github_release(
+            SERVER_GITHUB_REPO,
+            zed::GithubReleaseOptions {
+                require_assets: true,
+                pre_release: false,
+            },
+        )?;
+
+        let (platform, arch) = zed::current_platform();
+        let asset_name = format!(
+            "trigger-system-lsp-{arch}-{os}.{ext}",
+            arch = match arch {
+                zed::Architecture::Aarch64 => "aarch64",
+                zed::Architecture::X86 => "x86",
+                zed::Architecture::X8664 => "x86_64",
+            },
+            os = match platform {
+                zed::Os::Mac => "apple-darwin",
+                zed::Os::Linux => "unknown-linux-gnu",
+                zed::Os::Windows => "pc-windows-msvc",
+            },
+            ext = match platform {
+                zed::Os::Windows => "zip",
+                zed::Os::Mac | zed::Os::Linux => "tar.gz",
+            },
+        );
+
+        let asset = release
+            .assets
+            .iter()
+            .find(|asset| asset.name == asset_name)
+            .ok_or_else(|| format!("no asset found matching {asset_name:?}"))?;
+
+        let version_dir = format!("trigger-system-lsp-{}", release.version);
+        let binary_name = if matches!(platform, zed::Os::Windows) {
+            "trigger-system-lsp.exe"
+        } else {
+            "trigger-system-lsp"
         };
-        
-        Ok(zed::Command {
-            command: node_path,
-            args: vec![
-                lsp_path.to_string_lossy().to_string(),
-                "--stdio".to_string(),
-            ],
-            env: Default::default(),
-        })
+        let binary_path = format!("{version_dir}/{binary_name}");
+
+        if fs::metadata(&binary_path).is_err() {
+            zed::set_language_server_installation_status(
+                language_server_id,
+                &zed::LanguageServerInstallationStatus::Downloading,
+            );
+
+            let file_type = match platform {
+                zed::Os::Windows => zed::DownloadedFileType::Zip,
+                zed::Os::Mac | zed::Os::Linux => zed::DownloadedFileType::GzipTar,
+            };
+            zed::download_file(&asset.download_url, &version_dir, file_type)?;
+
+            // Archives uploaded by CI frequently lose the exec bit, and
+            // extraction doesn't always preserve it either.
+            zed::make_file_executable(&binary_path)?;
+        }
+
+        zed::set_language_server_installation_status(
+            language_server_id,
+            &zed::LanguageServerInstallationStatus::None,
+        );
+
+        self.cached_binary_path = Some(binary_path.clone());
+        Ok(binary_path)
+    }
+}
+
+impl zed::Extension for TriggerSystemExtension {
+    fn new() -> Self {
+        Self {
+            cached_server_path: None,
+            cached_binary_path: None,
+        }
+    }
+
+    fn language_server_command(
+        &mut self,
+        language_server_id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+    ) -> Result<zed::Command, String> {
+        let lsp_settings = zed::settings::LspSettings::for_worktree("trigger-system", worktree)
+            .ok()
+            .and_then(|settings| settings.binary);
+
+        // Merge `binary.env` into the command environment.
+        let env: Vec<(String, String)> = lsp_settings
+            .as_ref()
+            .and_then(|binary| binary.env.clone())
+            .map(|env| env.into_iter().collect())
+            .unwrap_or_default();
+
+        // `binary.path` is an explicit user override and always wins. It's
+        // typically a custom node executable, so it still needs the bundle
+        // path as its leading argument to actually launch the server.
+        if let Some(path) = lsp_settings.as_ref().and_then(|binary| binary.path.clone()) {
+            let server_path = self.server_script_path(language_server_id, worktree)?;
+            return Ok(zed::Command {
+                command: path,
+                args: build_args(lsp_settings.as_ref(), vec![server_path, "--stdio".to_string()]),
+                env,
+            });
+        }
+
+        // Prefer a standalone `trigger-system-lsp` binary in PATH over the
+        // bundled node server, so power users can run a self-compiled or
+        // system-packaged server without a Node dependency.
+        if let Some(standalone_path) = worktree.which("trigger-system-lsp") {
+            return Ok(zed::Command {
+                command: standalone_path,
+                args: build_args(lsp_settings.as_ref(), vec!["--stdio".to_string()]),
+                env,
+            });
+        }
+
+        // Managed install: prefer the npm-distributed bundle run with node,
+        // falling back to a prebuilt GitHub release binary when npm isn't
+        // available OR when there's no node in PATH to run the bundle with.
+        // Check for node first so a workspace without it doesn't pay for an
+        // npm install of the bundle it can't even run.
+        let node_bundle = worktree
+            .which("node")
+            .and_then(|node_path| {
+                self.server_script_path(language_server_id, worktree)
+                    .ok()
+                    .map(|server_path| (node_path, server_path))
+            });
+
+        match node_bundle {
+            Some((node_path, server_path)) => Ok(zed::Command {
+                command: node_path,
+                args: build_args(lsp_settings.as_ref(), vec![server_path, "--stdio".to_string()]),
+                env,
+            }),
+            None => {
+                let binary_path = self.download_server_from_github(language_server_id)?;
+
+                Ok(zed::Command {
+                    command: binary_path,
+                    args: build_args(lsp_settings.as_ref(), vec!["--stdio".to_string()]),
+                    env,
+                })
+            }
+        }
+    }
+
+    fn language_server_initialization_options(
+        &mut self,
+        _language_server_id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+    ) -> Result<Option<zed::serde_json::Value>, String> {
+        // The server reads trigger globs and evaluation mode at startup, so
+        // give it the same sensible defaults as `language_server_workspace_configuration`
+        // rather than an empty object.
+        let initialization_options = zed::settings::LspSettings::for_worktree("trigger-system", worktree)
+            .ok()
+            .and_then(|settings| settings.initialization_options)
+            .unwrap_or_else(default_trigger_system_config);
+
+        Ok(Some(initialization_options))
+    }
+
+    fn language_server_workspace_configuration(
+        &mut self,
+        _language_server_id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+    ) -> Result<Option<zed::serde_json::Value>, String> {
+        let settings = zed::settings::LspSettings::for_worktree("trigger-system", worktree)
+            .ok()
+            .and_then(|settings| settings.settings)
+            .unwrap_or_else(|| zed::serde_json::json!({ "triggerSystem": default_trigger_system_config() }));
+
+        Ok(Some(settings))
     }
 }
 